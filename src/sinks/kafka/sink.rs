@@ -1,12 +1,26 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use bytes::Bytes;
+use chrono::Utc;
+use dashmap::DashMap;
 use rdkafka::{
     consumer::{BaseConsumer, Consumer},
-    error::KafkaError,
-    producer::FutureProducer,
-    ClientConfig,
+    error::{KafkaError, KafkaResult},
+    producer::{oauth_token::OAuthToken, FutureProducer, Producer},
+    types::RDKafkaErrorCode,
+    util::Timeout,
+    ClientConfig, ClientContext, Statistics,
 };
+use serde::Deserialize;
 use snafu::{ResultExt, Snafu};
-use tokio::time::Duration;
-use tower::limit::ConcurrencyLimit;
+use tokio::time::{Duration, Instant};
+use tower::{limit::ConcurrencyLimit, ServiceExt};
 use vrl::path::OwnedTargetPath;
 
 use super::config::{KafkaRole, KafkaSinkConfig};
@@ -16,6 +30,379 @@ use crate::{
     sinks::prelude::*,
 };
 
+/// How often the overflow limiter sweeps its key map for idle entries.
+const OVERFLOW_EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long a key may go without traffic before its GCRA state is evicted.
+const OVERFLOW_EVICTION_IDLE_AGE: Duration = Duration::from_secs(300);
+
+/// Tracks per-message-key arrival rates using the Generic Cell Rate Algorithm
+/// (GCRA) and decides whether a key is "hot" enough that it should be
+/// rerouted away from its natural partition.
+///
+/// Each key gets a "theoretical arrival time" (TAT): the point at which the
+/// next event for that key is expected, given the configured rate. An event
+/// arriving too far ahead of its key's TAT is considered overflowing.
+struct OverflowLimiter {
+    states: Arc<DashMap<Bytes, Instant>>,
+    emission_interval: Duration,
+    burst_tolerance: Duration,
+    forced_keys: HashSet<Bytes>,
+}
+
+impl OverflowLimiter {
+    /// Builds a limiter for the given rate, rejecting configurations that would overflow or
+    /// NaN their way through `Duration::from_secs_f64` instead of panicking at that call.
+    fn new(
+        per_second_limit: f64,
+        burst_limit: f64,
+        forced_keys: HashSet<Bytes>,
+    ) -> Result<Self, BuildError> {
+        let invalid_rate = || BuildError::InvalidOverflowRate {
+            limit: per_second_limit,
+        };
+
+        if !per_second_limit.is_finite() || per_second_limit <= 0.0 {
+            return Err(invalid_rate());
+        }
+
+        let emission_secs = 1.0 / per_second_limit;
+        if !emission_secs.is_finite() || emission_secs > Duration::MAX.as_secs_f64() {
+            return Err(invalid_rate());
+        }
+
+        let emission_interval = Duration::from_secs_f64(emission_secs);
+        let burst_tolerance = emission_interval.mul_f64((burst_limit - 1.0).max(0.0));
+
+        let limiter = Self {
+            states: Arc::new(DashMap::new()),
+            emission_interval,
+            burst_tolerance,
+            forced_keys,
+        };
+        limiter.spawn_idle_evictor();
+        Ok(limiter)
+    }
+
+    /// Spawns a background task that periodically drops GCRA state for keys
+    /// that have gone quiet, so the map cannot grow unbounded with one-off
+    /// keys seen only once.
+    fn spawn_idle_evictor(&self) {
+        let states = Arc::clone(&self.states);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(OVERFLOW_EVICTION_INTERVAL);
+            loop {
+                interval.tick().await;
+                let now = Instant::now();
+                states.retain(|_, tat| {
+                    now.saturating_duration_since(*tat) < OVERFLOW_EVICTION_IDLE_AGE
+                });
+            }
+        });
+    }
+
+    /// Returns `true` if `key` is overflowing and should be rerouted or
+    /// stripped rather than produced under its own key.
+    fn check(&self, key: &[u8]) -> bool {
+        let overflowing = if self.forced_keys.contains(key) {
+            true
+        } else {
+            let now = Instant::now();
+            let mut overflowing = false;
+            self.states
+                .entry(Bytes::copy_from_slice(key))
+                .and_modify(|tat| {
+                    if now + self.burst_tolerance < *tat {
+                        overflowing = true;
+                    } else {
+                        *tat = (*tat).max(now) + self.emission_interval;
+                    }
+                })
+                .or_insert_with(|| now + self.emission_interval);
+            overflowing
+        };
+
+        // Forced keys are always reported as overflowing too, so the metric reflects every
+        // rerouted event, not just the ones the rate limiter itself caught.
+        if overflowing {
+            emit!(KafkaKeyOverflowed {
+                key: Bytes::copy_from_slice(key)
+            });
+        }
+        overflowing
+    }
+}
+
+#[derive(Debug)]
+struct KafkaKeyOverflowed {
+    key: Bytes,
+}
+
+impl InternalEvent for KafkaKeyOverflowed {
+    fn emit(self) {
+        debug!(
+            message = "Message key is overflowing; the event is rerouted to the overflow topic or has its key stripped.",
+            key = %String::from_utf8_lossy(&self.key),
+        );
+        // The key itself must not become a metric label: overflowing keys are by definition
+        // high-cardinality, and a label here would mean unbounded series in the metrics backend.
+        counter!("kafka_overflow_rerouted_total", 1);
+    }
+}
+
+/// How a Kafka delivery error should be treated: a fatal error will never
+/// succeed no matter how many times it is retried, while a transient one is
+/// worth leaving to rdkafka's internal retry handling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeliveryErrorClass {
+    Fatal,
+    Transient,
+}
+
+/// Classifies a Kafka delivery error by walking its source chain looking for
+/// the underlying `rdkafka` error code, so that errors are split into fatal
+/// and transient ones instead of retrying everything forever. An error with
+/// no `rdkafka` error code in its chain is treated as transient.
+fn classify_delivery_error(error: &(dyn std::error::Error + 'static)) -> DeliveryErrorClass {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if let Some(kafka_error) = error.downcast_ref::<KafkaError>() {
+            return match kafka_error.rdkafka_error_code() {
+                Some(
+                    RDKafkaErrorCode::MessageSizeTooLarge
+                    | RDKafkaErrorCode::Authentication
+                    | RDKafkaErrorCode::UnknownTopicOrPartition
+                    | RDKafkaErrorCode::OffsetOutOfRange,
+                ) => DeliveryErrorClass::Fatal,
+                _ => DeliveryErrorClass::Transient,
+            };
+        }
+        source = error.source();
+    }
+    DeliveryErrorClass::Transient
+}
+
+#[derive(Debug)]
+struct KafkaFatalDeliveryError {
+    error: String,
+}
+
+impl InternalEvent for KafkaFatalDeliveryError {
+    fn emit(self) {
+        error!(
+            message = "Kafka delivery failed with a fatal error and will not be retried.",
+            error = %self.error,
+        );
+        counter!("kafka_fatal_delivery_errors_total", 1);
+    }
+}
+
+/// Number of consecutive fatal delivery errors after which the sink
+/// considers the producer's configuration broken (bad topic, bad
+/// credentials, oversized messages) and stops processing rather than
+/// continuing to burn through the batch.
+const FATAL_ERROR_TRIP_THRESHOLD: u32 = 5;
+
+/// Tracks fatal delivery errors observed by [`classify_delivery_error`] and
+/// trips once they persist, so a misconfigured sink (bad topic, bad
+/// credentials, oversized messages) fails loudly instead of quietly
+/// discarding events one fatal error at a time forever.
+#[derive(Clone, Default)]
+struct FatalErrorBreaker {
+    fatal_errors: Arc<AtomicU32>,
+}
+
+impl FatalErrorBreaker {
+    /// Returns `true` once persistent fatal errors have tripped the breaker.
+    fn is_tripped(&self) -> bool {
+        self.fatal_errors.load(Ordering::Relaxed) >= FATAL_ERROR_TRIP_THRESHOLD
+    }
+
+    /// Records a delivery error, emitting a distinct event and counting
+    /// towards the trip threshold if it is fatal. Transient errors (the ones
+    /// rdkafka's own retry loop already handles) are left alone.
+    fn observe_error(&self, error: &(dyn std::error::Error + 'static)) {
+        if classify_delivery_error(error) == DeliveryErrorClass::Fatal {
+            emit!(KafkaFatalDeliveryError {
+                error: error.to_string()
+            });
+            self.fatal_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Clears the trip count after a successful delivery, so the breaker
+    /// trips on *persistent* fatal errors rather than on a handful that
+    /// happened to occur once over the sink's entire lifetime.
+    fn reset(&self) {
+        self.fatal_errors.store(0, Ordering::Relaxed);
+    }
+}
+
+/// An OAuth client secret that redacts itself on `Debug`, so an accidental
+/// `{:?}` of the surrounding config (in a log, panic, or trace) never leaks
+/// the credential.
+#[derive(Clone)]
+struct RedactedSecret(String);
+
+impl RedactedSecret {
+    fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for RedactedSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("\"**REDACTED**\"")
+    }
+}
+
+/// Configuration needed to fetch a SASL/OAUTHBEARER token from an external
+/// identity provider's token endpoint, handed to the client as a client
+/// credentials grant.
+#[derive(Debug, Clone)]
+struct KafkaOAuthTokenConfig {
+    token_endpoint: String,
+    client_id: String,
+    client_secret: RedactedSecret,
+    scope: Option<String>,
+    extensions: Vec<(String, String)>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KafkaOAuthTokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+/// `ClientContext` used for the producer and healthcheck consumer when
+/// SASL/OAUTHBEARER authentication is configured. Delegates statistics
+/// reporting to the usual [`KafkaStatisticsContext`] and additionally
+/// answers librdkafka's token-refresh callback by fetching a fresh bearer
+/// token from the configured token endpoint.
+#[derive(Debug, Clone)]
+pub(crate) struct KafkaOAuthContext {
+    stats: KafkaStatisticsContext,
+    token_config: Arc<KafkaOAuthTokenConfig>,
+}
+
+impl KafkaOAuthContext {
+    fn new(token_config: KafkaOAuthTokenConfig) -> Self {
+        Self {
+            stats: KafkaStatisticsContext::default(),
+            token_config: Arc::new(token_config),
+        }
+    }
+}
+
+impl ClientContext for KafkaOAuthContext {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = true;
+
+    fn stats(&self, statistics: Statistics) {
+        self.stats.stats(statistics);
+    }
+
+    fn generate_oauth_token(
+        &self,
+        _oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn std::error::Error>> {
+        let config = Arc::clone(&self.token_config);
+
+        // This callback can fire while librdkafka's polling is driven from a tokio worker
+        // thread, so the blocking HTTP client must not run inline: `block_in_place` hands this
+        // thread's other work to another worker for the duration of the call instead of
+        // stalling the runtime (or, worse, letting `reqwest::blocking` try to spin up its own
+        // runtime on top of tokio's).
+        tokio::task::block_in_place(move || {
+            let response: KafkaOAuthTokenResponse = reqwest::blocking::Client::new()
+                .post(&config.token_endpoint)
+                .form(&[
+                    ("grant_type", "client_credentials"),
+                    ("client_id", config.client_id.as_str()),
+                    ("client_secret", config.client_secret.expose()),
+                    ("scope", config.scope.as_deref().unwrap_or_default()),
+                ])
+                .send()?
+                .error_for_status()?
+                .json()?;
+
+            Ok(OAuthToken {
+                token: response.access_token,
+                principal_name: config.client_id.clone(),
+                lifetime_ms: Utc::now().timestamp_millis() + response.expires_in * 1000,
+                extensions: config.extensions.clone(),
+            })
+        })
+    }
+}
+
+/// Abstracts over the producer's context flavor (plain statistics vs. OAuth)
+/// so the transactional code path below doesn't need to be generic over
+/// `C: ClientContext`; it only ever needs the handful of transaction calls.
+trait TransactionalProducer: Send + Sync {
+    fn init_transactions(&self, timeout: Timeout) -> KafkaResult<()>;
+    fn begin_transaction(&self) -> KafkaResult<()>;
+    fn commit_transaction(&self, timeout: Timeout) -> KafkaResult<()>;
+    fn abort_transaction(&self, timeout: Timeout) -> KafkaResult<()>;
+}
+
+impl<C: ClientContext + 'static> TransactionalProducer for FutureProducer<C> {
+    fn init_transactions(&self, timeout: Timeout) -> KafkaResult<()> {
+        Producer::init_transactions(self, timeout)
+    }
+
+    fn begin_transaction(&self) -> KafkaResult<()> {
+        Producer::begin_transaction(self)
+    }
+
+    fn commit_transaction(&self, timeout: Timeout) -> KafkaResult<()> {
+        Producer::commit_transaction(self, timeout)
+    }
+
+    fn abort_transaction(&self, timeout: Timeout) -> KafkaResult<()> {
+        Producer::abort_transaction(self, timeout)
+    }
+}
+
+#[derive(Debug)]
+struct KafkaTransactionOutcome {
+    committed: bool,
+    batch_size: usize,
+}
+
+impl InternalEvent for KafkaTransactionOutcome {
+    fn emit(self) {
+        if self.committed {
+            debug!(
+                message = "Kafka transaction committed.",
+                batch_size = self.batch_size
+            );
+            counter!("kafka_transactions_committed_total", 1);
+        } else {
+            error!(
+                message = "Kafka transaction aborted.",
+                batch_size = self.batch_size
+            );
+            counter!("kafka_transactions_aborted_total", 1);
+        }
+    }
+}
+
+/// Resolves the per-event outcome of one transactional batch, extracted as a pure function so
+/// the acking behavior can be tested without a live transactional producer. `all_sent` is
+/// whether every produce call in the batch succeeded; `commit_succeeded` is whether the
+/// transaction was actually committed (the caller only attempts a commit, and thus only passes
+/// `true`, when `all_sent` already held). Returns whether the batch counts as committed and the
+/// status every event's finalizers should be resolved to.
+fn resolve_transactional_batch(all_sent: bool, commit_succeeded: bool) -> (bool, EventStatus) {
+    let committed = all_sent && commit_succeeded;
+    let status = if committed {
+        EventStatus::Delivered
+    } else {
+        EventStatus::Errored
+    };
+    (committed, status)
+}
+
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)))]
 pub(super) enum BuildError {
@@ -23,8 +410,27 @@ pub(super) enum BuildError {
     KafkaCreateFailed { source: KafkaError },
     #[snafu(display("invalid topic template: {}", source))]
     TopicTemplate { source: TemplateParseError },
+    #[snafu(display("initializing kafka transactions failed: {}", source))]
+    KafkaTransactionsInitFailed { source: KafkaError },
+    #[snafu(display("creating kafka consumer for healthcheck failed: {}", source))]
+    KafkaConsumerCreateFailed { source: KafkaError },
+    #[snafu(display("fetching kafka metadata for healthcheck failed: {}", source))]
+    KafkaMetadataFetchFailed { source: KafkaError },
+    #[snafu(display("kafka topic {:?} does not exist", topic))]
+    KafkaTopicNotFound { topic: String },
+    #[snafu(display("kafka topic {:?} has no partitions", topic))]
+    KafkaTopicNoPartitions { topic: String },
+    #[snafu(display(
+        "invalid `overflow_per_second_limit` {}: must be a positive, finite number",
+        limit
+    ))]
+    InvalidOverflowRate { limit: f64 },
 }
 
+/// Default timeout for the healthcheck's metadata fetch when
+/// `healthcheck_timeout_secs` is not configured.
+const DEFAULT_HEALTHCHECK_TIMEOUT_SECS: u64 = 30;
+
 pub struct KafkaSink {
     transformer: Transformer,
     encoder: Encoder<()>,
@@ -32,6 +438,10 @@ pub struct KafkaSink {
     topic: Template,
     key_field: Option<OwnedTargetPath>,
     headers_key: Option<OwnedTargetPath>,
+    overflow: Option<OverflowLimiter>,
+    overflow_topic: Option<Template>,
+    transactional_producer: Option<Arc<dyn TransactionalProducer>>,
+    transactional_batch_size: usize,
 }
 
 pub(crate) fn create_producer(
@@ -43,29 +453,118 @@ pub(crate) fn create_producer(
     Ok(producer)
 }
 
+/// Like [`create_producer`], but authenticates with SASL/OAUTHBEARER and
+/// refreshes its token through [`KafkaOAuthContext::generate_oauth_token`]
+/// whenever librdkafka signals that the current one is expiring.
+pub(crate) fn create_oauth_producer(
+    client_config: ClientConfig,
+    token_config: KafkaOAuthTokenConfig,
+) -> crate::Result<FutureProducer<KafkaOAuthContext>> {
+    let producer = client_config
+        .create_with_context(KafkaOAuthContext::new(token_config))
+        .context(KafkaCreateFailedSnafu)?;
+    Ok(producer)
+}
+
+/// Builds the OAuth token fetch configuration from `config`'s `oauth_*`
+/// fields, or `None` if SASL/OAUTHBEARER authentication is not configured.
+fn oauth_token_config(config: &KafkaSinkConfig) -> Option<KafkaOAuthTokenConfig> {
+    let token_endpoint = config.oauth_token_endpoint.clone()?;
+    Some(KafkaOAuthTokenConfig {
+        token_endpoint,
+        client_id: config.oauth_client_id.clone().unwrap_or_default(),
+        client_secret: RedactedSecret(config.oauth_client_secret.clone().unwrap_or_default()),
+        scope: config.oauth_scope.clone(),
+        extensions: config.oauth_extensions.clone().into_iter().collect(),
+    })
+}
+
+/// Default number of events batched into a single Kafka transaction when
+/// `transactional_enabled` is set but `transactional_batch_size` is not.
+const DEFAULT_TRANSACTIONAL_BATCH_SIZE: usize = 500;
+
 impl KafkaSink {
     pub(crate) fn new(config: KafkaSinkConfig) -> crate::Result<Self> {
-        let producer_config = config.to_rdkafka(KafkaRole::Producer)?;
-        let producer = create_producer(producer_config)?;
+        let mut producer_config = config.to_rdkafka(KafkaRole::Producer)?;
+        if config.transactional_enabled {
+            // A transactional producer must be idempotent: otherwise a retried send could be
+            // duplicated *within* an otherwise-atomic transaction.
+            producer_config
+                .set("enable.idempotence", "true")
+                .set("transactional.id", &config.transactional_id);
+        }
+
+        let (service, transactional_producer) = match oauth_token_config(&config) {
+            Some(token_config) => {
+                let producer = create_oauth_producer(producer_config, token_config)?;
+                let transactional_producer = config
+                    .transactional_enabled
+                    .then(|| Arc::new(producer.clone()) as Arc<dyn TransactionalProducer>);
+                (KafkaService::new(producer), transactional_producer)
+            }
+            None => {
+                let producer = create_producer(producer_config)?;
+                let transactional_producer = config
+                    .transactional_enabled
+                    .then(|| Arc::new(producer.clone()) as Arc<dyn TransactionalProducer>);
+                (KafkaService::new(producer), transactional_producer)
+            }
+        };
+
+        if let Some(txn_producer) = &transactional_producer {
+            txn_producer
+                .init_transactions(Timeout::After(Duration::from_secs(30)))
+                .context(KafkaTransactionsInitFailedSnafu)?;
+        }
+
         let transformer = config.encoding.transformer();
         let serializer = config.encoding.build()?;
         let encoder = Encoder::<()>::new(serializer);
 
+        let overflow = config
+            .overflow_enabled
+            .then(|| {
+                let forced_keys = config
+                    .overflow_forced_keys
+                    .iter()
+                    .map(|key| Bytes::copy_from_slice(key.as_bytes()))
+                    .collect();
+                OverflowLimiter::new(
+                    config.overflow_per_second_limit,
+                    config.overflow_burst_limit,
+                    forced_keys,
+                )
+            })
+            .transpose()?;
+
         Ok(KafkaSink {
             headers_key: config.headers_key.map(|key| key.0),
             transformer,
             encoder,
-            service: KafkaService::new(producer),
+            service,
             topic: config.topic,
             key_field: config.key_field.map(|key| key.0),
+            overflow,
+            overflow_topic: config.overflow_topic,
+            transactional_producer,
+            transactional_batch_size: config
+                .transactional_batch_size
+                .unwrap_or(DEFAULT_TRANSACTIONAL_BATCH_SIZE),
         })
     }
 
+    /// Returns the key bytes `event` would be produced under, honoring `key_field`.
+    fn extract_key(key_field: Option<&OwnedTargetPath>, event: &Event) -> Option<Bytes> {
+        let log = event.maybe_as_log()?;
+        let value = log.get(key_field?)?;
+        value.as_bytes()
+    }
+
     async fn run_inner(self: Box<Self>, input: BoxStream<'_, Event>) -> Result<(), ()> {
-        // rdkafka will internally retry forever, so we need some limit to prevent this from overflowing.
-        // 64 should be plenty concurrency here, as a rdkafka send operation does not block until its underlying
-        // buffer is full.
-        let service = ConcurrencyLimit::new(self.service.clone(), 64);
+        let key_field = self.key_field.clone();
+        let overflow = self.overflow;
+        let overflow_topic = self.overflow_topic;
+        let topic = self.topic;
 
         let request_builder = KafkaRequestBuilder {
             key_field: self.key_field,
@@ -73,11 +572,32 @@ impl KafkaSink {
             encoder: (self.transformer, self.encoder),
         };
 
-        input
-            .filter_map(|event| {
+        let requests = input
+            .filter_map(move |mut event| {
+                // An overflowing key either gets rerouted to a dedicated overflow topic, or has
+                // its key stripped so the broker round-robins it across partitions instead of
+                // hammering the single partition its key would normally hash to.
+                let overflowing = overflow.as_ref().map_or(false, |overflow| {
+                    Self::extract_key(key_field.as_ref(), &event)
+                        .is_some_and(|key| overflow.check(&key))
+                });
+
+                let topic_template = if overflowing {
+                    overflow_topic.as_ref().unwrap_or(&topic)
+                } else {
+                    &topic
+                };
+
+                if overflowing && overflow_topic.is_none() {
+                    if let (Some(path), Some(log)) = (key_field.as_ref(), event.maybe_as_log_mut())
+                    {
+                        log.remove(path);
+                    }
+                }
+
                 // Compute the topic.
                 future::ready(
-                    self.topic
+                    topic_template
                         .render_string(&event)
                         .map_err(|error| {
                             emit!(TemplateRenderingError {
@@ -99,18 +619,132 @@ impl KafkaSink {
                     }
                     Ok(req) => Some(req),
                 }
-            })
-            .into_driver(service)
-            .protocol("kafka")
-            .protocol("kafka")
-            .run()
-            .await
+            });
+
+        match self.transactional_producer {
+            // The transactional send path: events are batched into bounded,
+            // single-writer transactions. Every request in a batch is sent through
+            // the same producer before the batch is committed as a unit; any fatal
+            // send error aborts the whole batch rather than partially committing it.
+            Some(txn_producer) => {
+                // Still bound the number of produce calls in flight at once, same as the
+                // non-transactional path below; a batch can be as large as
+                // `transactional_batch_size` (500 by default) and firing all of them
+                // concurrently with no limit would be unbounded in the same way.
+                let service = ConcurrencyLimit::new(self.service, 64);
+                let mut chunks =
+                    std::pin::pin!(requests.chunks(self.transactional_batch_size.max(1)));
+
+                while let Some(chunk) = chunks.next().await {
+                    let batch_size = chunk.len();
+
+                    // Finalizers are pulled off each request up front, before even attempting to
+                    // begin the transaction, because the request is consumed by `oneshot` and
+                    // every failure path below (begin, send, commit) needs to resolve them to
+                    // whatever actually happened to the batch - none of them should be dropped
+                    // with their acknowledgement left unresolved.
+                    let (requests, finalizers): (Vec<_>, Vec<_>) = chunk
+                        .into_iter()
+                        .map(|mut req| {
+                            let finalizers = req.take_finalizers();
+                            (req, finalizers)
+                        })
+                        .unzip();
+
+                    if let Err(error) = txn_producer.begin_transaction() {
+                        error!(message = "Failed to begin Kafka transaction.", %error);
+                        for finalizers in finalizers {
+                            finalizers.update_status(EventStatus::Errored);
+                        }
+                        return Err(());
+                    }
+
+                    let results = future::join_all(
+                        requests.into_iter().map(|req| service.clone().oneshot(req)),
+                    )
+                    .await;
+
+                    let all_sent = results.iter().all(Result::is_ok);
+                    let commit_succeeded = all_sent
+                        && txn_producer
+                            .commit_transaction(Timeout::After(Duration::from_secs(60)))
+                            .is_ok();
+
+                    if !all_sent {
+                        let _ =
+                            txn_producer.abort_transaction(Timeout::After(Duration::from_secs(60)));
+                    }
+
+                    let (committed, status) =
+                        resolve_transactional_batch(all_sent, commit_succeeded);
+                    for finalizers in finalizers {
+                        finalizers.update_status(status);
+                    }
+
+                    emit!(KafkaTransactionOutcome {
+                        committed,
+                        batch_size
+                    });
+                    if !committed {
+                        return Err(());
+                    }
+                }
+
+                Ok(())
+            }
+            // The normal (non-transactional) send path: an unbounded,
+            // concurrency-limited pipe of individual produce calls. rdkafka will
+            // internally retry transient errors forever, so we still need a limit
+            // to prevent this from overflowing. 64 should be plenty concurrency
+            // here, as a rdkafka send operation does not block until its
+            // underlying buffer is full. Fatal errors (bad topic, bad
+            // credentials, oversized messages) are classified out below and
+            // never benefit from that retry loop, so repeated ones trip
+            // `breaker` instead of being retried forever.
+            None => {
+                let breaker = FatalErrorBreaker::default();
+                let limited = {
+                    let breaker = breaker.clone();
+                    ConcurrencyLimit::new(self.service, 64).then(move |result| {
+                        let breaker = breaker.clone();
+                        async move {
+                            match &result {
+                                Ok(_) => breaker.reset(),
+                                Err(error) => breaker.observe_error(error),
+                            }
+                            result
+                        }
+                    })
+                };
+
+                // Stop admitting new requests to the driver as soon as the breaker trips,
+                // rather than draining the rest of the input stream first. In-flight sends
+                // (bounded by the concurrency limit above) still finish, but nothing new
+                // is started once the sink is considered broken.
+                let gate = breaker.clone();
+                requests
+                    .take_while(move |_| future::ready(!gate.is_tripped()))
+                    .into_driver(limited)
+                    .protocol("kafka")
+                    .run()
+                    .await?;
+
+                if breaker.is_tripped() {
+                    error!(
+                        message = "Kafka sink is shutting down after repeated fatal delivery errors; check topic and credential configuration.",
+                    );
+                    return Err(());
+                }
+
+                Ok(())
+            }
+        }
     }
 }
 
 pub(crate) async fn healthcheck(config: KafkaSinkConfig) -> crate::Result<()> {
     trace!("Healthcheck started.");
-    let client = config.to_rdkafka(KafkaRole::Consumer).unwrap();
+    let client = config.to_rdkafka(KafkaRole::Consumer)?;
     let topic = match config.topic.render_string(&LogEvent::from_str_legacy("")) {
         Ok(topic) => Some(topic),
         Err(error) => {
@@ -121,23 +755,248 @@ pub(crate) async fn healthcheck(config: KafkaSinkConfig) -> crate::Result<()> {
             None
         }
     };
+    let oauth_token_config = oauth_token_config(&config);
+    let timeout = Duration::from_secs(
+        config
+            .healthcheck_timeout_secs
+            .unwrap_or(DEFAULT_HEALTHCHECK_TIMEOUT_SECS),
+    );
+
+    let topic_for_blocking = topic.clone();
+    let metadata = tokio::task::spawn_blocking(move || -> crate::Result<_> {
+        let topic_ref = topic_for_blocking.as_deref();
 
-    tokio::task::spawn_blocking(move || {
-        let consumer: BaseConsumer = client.create().unwrap();
-        let topic = topic.as_ref().map(|topic| &topic[..]);
+        let metadata = match oauth_token_config {
+            Some(token_config) => {
+                let consumer: BaseConsumer<KafkaOAuthContext> = client
+                    .create_with_context(KafkaOAuthContext::new(token_config))
+                    .context(KafkaConsumerCreateFailedSnafu)?;
+                consumer.fetch_metadata(topic_ref, timeout)
+            }
+            None => {
+                let consumer: BaseConsumer =
+                    client.create().context(KafkaConsumerCreateFailedSnafu)?;
+                consumer.fetch_metadata(topic_ref, timeout)
+            }
+        }
+        .context(KafkaMetadataFetchFailedSnafu)?;
 
-        consumer
-            .fetch_metadata(topic, Duration::from_secs(3))
-            .map(|_| ())
+        Ok(metadata)
     })
     .await??;
+
+    debug!(
+        message = "Healthcheck reached the Kafka broker(s).",
+        broker_count = metadata.brokers().len(),
+    );
+
+    if let Some(topic) = topic {
+        let topic_info = metadata
+            .topics()
+            .iter()
+            .find(|topic_metadata| topic_metadata.name() == topic)
+            .map(|topic_metadata| {
+                (
+                    topic_metadata.error().is_some(),
+                    topic_metadata.partitions().len(),
+                )
+            });
+
+        let partition_count = verify_topic_health(&topic, topic_info)?;
+        debug!(
+            message = "Healthcheck verified topic partitions.",
+            %topic,
+            partition_count,
+        );
+    }
+
     trace!("Healthcheck completed.");
     Ok(())
 }
 
+/// Decision logic behind the healthcheck's topic verification, pulled out as a pure function
+/// over the two bits of metadata it actually needs so it can be unit-tested without a live
+/// broker. `topic_info` is `Some((topic_has_error, partition_count))` for the matching entry in
+/// `Metadata::topics()`, or `None` if the topic was absent from the response outright.
+///
+/// The broker reports an unknown topic as a topic entry with an error code and no partitions,
+/// rather than simply omitting it, so the error case has to be checked before the partition
+/// count.
+fn verify_topic_health(
+    topic: &str,
+    topic_info: Option<(bool, usize)>,
+) -> Result<usize, BuildError> {
+    match topic_info {
+        None => Err(BuildError::KafkaTopicNotFound {
+            topic: topic.to_owned(),
+        }),
+        Some((true, _)) => Err(BuildError::KafkaTopicNotFound {
+            topic: topic.to_owned(),
+        }),
+        Some((false, 0)) => Err(BuildError::KafkaTopicNoPartitions {
+            topic: topic.to_owned(),
+        }),
+        Some((false, partition_count)) => Ok(partition_count),
+    }
+}
+
 #[async_trait]
 impl StreamSink<Event> for KafkaSink {
     async fn run(self: Box<Self>, input: BoxStream<'_, Event>) -> Result<(), ()> {
         self.run_inner(input).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_oversized_message_as_fatal() {
+        let error = KafkaError::MessageProduction(RDKafkaErrorCode::MessageSizeTooLarge);
+        assert_eq!(classify_delivery_error(&error), DeliveryErrorClass::Fatal);
+    }
+
+    #[test]
+    fn classifies_unknown_topic_as_fatal() {
+        let error = KafkaError::MessageProduction(RDKafkaErrorCode::UnknownTopicOrPartition);
+        assert_eq!(classify_delivery_error(&error), DeliveryErrorClass::Fatal);
+    }
+
+    #[test]
+    fn classifies_queue_full_as_transient() {
+        let error = KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull);
+        assert_eq!(
+            classify_delivery_error(&error),
+            DeliveryErrorClass::Transient
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn overflow_limiter_blocks_bursts_and_recovers_with_the_configured_rate() {
+        let limiter = OverflowLimiter::new(1.0, 1.0, HashSet::new()).unwrap();
+
+        assert!(!limiter.check(b"hot-key"));
+        // A second message for the same key in the same instant arrives well ahead of its TAT.
+        assert!(limiter.check(b"hot-key"));
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        // The rate has caught up, so the key is allowed through again.
+        assert!(!limiter.check(b"hot-key"));
+    }
+
+    #[tokio::test]
+    async fn overflow_limiter_always_reports_forced_keys_as_overflowing() {
+        let mut forced_keys = HashSet::new();
+        forced_keys.insert(Bytes::from_static(b"always-rerouted"));
+        let limiter = OverflowLimiter::new(1_000.0, 1_000.0, forced_keys).unwrap();
+
+        assert!(limiter.check(b"always-rerouted"));
+        assert!(limiter.check(b"always-rerouted"));
+    }
+
+    #[test]
+    fn overflow_limiter_rejects_non_positive_rates() {
+        assert!(matches!(
+            OverflowLimiter::new(0.0, 1.0, HashSet::new()),
+            Err(BuildError::InvalidOverflowRate { .. })
+        ));
+        assert!(matches!(
+            OverflowLimiter::new(-1.0, 1.0, HashSet::new()),
+            Err(BuildError::InvalidOverflowRate { .. })
+        ));
+        assert!(matches!(
+            OverflowLimiter::new(f64::NAN, 1.0, HashSet::new()),
+            Err(BuildError::InvalidOverflowRate { .. })
+        ));
+    }
+
+    #[test]
+    fn overflow_limiter_rejects_a_rate_too_small_to_convert_to_a_duration() {
+        assert!(matches!(
+            OverflowLimiter::new(f64::MIN_POSITIVE, 1.0, HashSet::new()),
+            Err(BuildError::InvalidOverflowRate { .. })
+        ));
+    }
+
+    #[test]
+    fn fatal_error_breaker_trips_after_threshold_and_resets() {
+        let breaker = FatalErrorBreaker::default();
+        let fatal = KafkaError::MessageProduction(RDKafkaErrorCode::MessageSizeTooLarge);
+        let transient = KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull);
+
+        for _ in 0..FATAL_ERROR_TRIP_THRESHOLD - 1 {
+            breaker.observe_error(&fatal);
+            assert!(!breaker.is_tripped());
+        }
+
+        breaker.observe_error(&fatal);
+        assert!(breaker.is_tripped());
+
+        breaker.reset();
+        assert!(!breaker.is_tripped());
+
+        // Transient errors never count towards the trip threshold, no matter how many.
+        for _ in 0..FATAL_ERROR_TRIP_THRESHOLD * 2 {
+            breaker.observe_error(&transient);
+        }
+        assert!(!breaker.is_tripped());
+    }
+
+    #[test]
+    fn resolve_transactional_batch_delivers_only_on_commit() {
+        assert_eq!(
+            resolve_transactional_batch(true, true),
+            (true, EventStatus::Delivered)
+        );
+    }
+
+    #[test]
+    fn resolve_transactional_batch_errors_the_batch_when_commit_fails() {
+        assert_eq!(
+            resolve_transactional_batch(true, false),
+            (false, EventStatus::Errored)
+        );
+    }
+
+    #[test]
+    fn resolve_transactional_batch_errors_the_batch_when_a_send_failed() {
+        // A failed send means the batch is aborted, not committed, regardless of what the
+        // caller would have passed for `commit_succeeded` (which it never attempts in this case).
+        assert_eq!(
+            resolve_transactional_batch(false, true),
+            (false, EventStatus::Errored)
+        );
+    }
+
+    #[test]
+    fn verify_topic_health_rejects_a_missing_topic() {
+        assert!(matches!(
+            verify_topic_health("missing", None),
+            Err(BuildError::KafkaTopicNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_topic_health_rejects_a_topic_reported_with_an_error() {
+        // librdkafka reports an unknown topic as an entry with an error code rather than
+        // omitting it, so this must be classified the same as an outright missing topic.
+        assert!(matches!(
+            verify_topic_health("errored", Some((true, 0))),
+            Err(BuildError::KafkaTopicNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_topic_health_rejects_a_topic_with_no_partitions() {
+        assert!(matches!(
+            verify_topic_health("empty", Some((false, 0))),
+            Err(BuildError::KafkaTopicNoPartitions { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_topic_health_accepts_a_populated_topic() {
+        assert!(matches!(verify_topic_health("ok", Some((false, 3))), Ok(3)));
+    }
+}